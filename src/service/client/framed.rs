@@ -0,0 +1,371 @@
+//! Bridges a [`ClientSocket`] to an arbitrary byte transport using the LSP wire format.
+
+use std::io;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use futures::io::{AsyncRead, AsyncWrite};
+use futures::sink::Sink;
+use futures::stream::Stream;
+
+use super::{ClientSocket, Pending, ServerState, State};
+use crate::jsonrpc::{Request, Response};
+
+/// How much of the currently-serialized outbound message has already been copied out
+/// through [`poll_read`](Framed::poll_read).
+#[derive(Debug, Default)]
+enum ReadState {
+    /// Nothing is queued; the next `poll_read` must pull a fresh `Request` from the socket.
+    #[default]
+    Idle,
+    /// `bytes[pos..]` is still waiting to be copied into a caller-supplied buffer.
+    Buffered { bytes: Vec<u8>, pos: usize },
+}
+
+/// Adapts a [`ClientSocket`] into an [`AsyncRead`] + [`AsyncWrite`] byte stream framed as
+/// `Content-Length: N\r\n\r\n{json}`, the wire format LSP uses over stdio, pipes, and sockets.
+///
+/// Bytes read out are client-bound `Request`s serialized to wire format; bytes written in
+/// are expected to be `Response`s in the same format, routed back through `Pending` once a
+/// complete message has been buffered.
+#[derive(Debug)]
+pub struct Framed {
+    rx: ClientSocket,
+    pending: std::sync::Arc<Pending>,
+    state: std::sync::Arc<ServerState>,
+    read_state: ReadState,
+    write_buf: Vec<u8>,
+}
+
+impl Framed {
+    /// Sanity cap on how many bytes of header we'll buffer before giving up and erroring
+    /// out, so a peer that never sends the `\r\n\r\n` terminator can't grow `write_buf`
+    /// unboundedly with no error ever surfaced.
+    const MAX_HEADER_LEN: usize = 8 * 1024;
+
+    /// Sanity cap on a single message's declared `Content-Length`, so a corrupt or
+    /// malicious header can't wedge the adapter buffering an unbounded body forever.
+    const MAX_BODY_LEN: usize = 64 * 1024 * 1024;
+
+    pub(super) fn new(socket: ClientSocket) -> Self {
+        let pending = socket.pending.clone();
+        let state = socket.state.clone();
+        Framed {
+            rx: socket,
+            pending,
+            state,
+            read_state: ReadState::default(),
+            write_buf: Vec::new(),
+        }
+    }
+
+    fn serialize(request: &Request) -> io::Result<Vec<u8>> {
+        let body = serde_json::to_vec(request).map_err(io::Error::other)?;
+        let mut message = format!("Content-Length: {}\r\n\r\n", body.len()).into_bytes();
+        message.extend_from_slice(&body);
+        Ok(message)
+    }
+
+    /// Extracts one complete `Content-Length`-framed message from `write_buf`, if present,
+    /// leaving any trailing partial message buffered for the next call.
+    ///
+    /// Returns `Err` on malformed framing (a non-UTF-8 header, a missing or unparseable
+    /// `Content-Length`, a header that never terminates, or a declared length past
+    /// `MAX_BODY_LEN`) rather than treating it as "not enough data yet" and buffering it
+    /// forever.
+    fn take_message(&mut self) -> io::Result<Option<Vec<u8>>> {
+        let header_end = match find_subslice(&self.write_buf, b"\r\n\r\n") {
+            Some(header_end) => header_end,
+            None if self.write_buf.len() > Self::MAX_HEADER_LEN => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!(
+                        "no Content-Length header terminator within the first {} bytes",
+                        Self::MAX_HEADER_LEN
+                    ),
+                ));
+            }
+            None => return Ok(None),
+        };
+
+        let header = std::str::from_utf8(&self.write_buf[..header_end]).map_err(|_| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                "message header is not valid UTF-8",
+            )
+        })?;
+        let len: usize = header
+            .lines()
+            .find_map(|line| line.strip_prefix("Content-Length: "))
+            .and_then(|len| len.trim().parse().ok())
+            .ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "missing or malformed Content-Length header",
+                )
+            })?;
+
+        if len > Self::MAX_BODY_LEN {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "Content-Length {len} exceeds the {}-byte sanity cap",
+                    Self::MAX_BODY_LEN
+                ),
+            ));
+        }
+
+        let body_start = header_end + 4;
+        let body_end = body_start.checked_add(len).ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                "Content-Length overflows a buffer offset",
+            )
+        })?;
+        if self.write_buf.len() < body_end {
+            return Ok(None);
+        }
+
+        let body = self.write_buf[body_start..body_end].to_vec();
+        self.write_buf.drain(..body_end);
+        Ok(Some(body))
+    }
+}
+
+fn find_subslice(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack
+        .windows(needle.len())
+        .position(|window| window == needle)
+}
+
+impl AsyncRead for Framed {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        loop {
+            if let ReadState::Buffered { bytes, pos } = &mut self.read_state {
+                let remaining = &bytes[*pos..];
+                let n = remaining.len().min(buf.len());
+                buf[..n].copy_from_slice(&remaining[..n]);
+                *pos += n;
+                if *pos == bytes.len() {
+                    self.read_state = ReadState::Idle;
+                }
+                return Poll::Ready(Ok(n));
+            }
+
+            match Pin::new(&mut self.rx).poll_next(cx) {
+                Poll::Ready(Some(request)) => {
+                    let bytes = Framed::serialize(&request)?;
+                    self.read_state = ReadState::Buffered { bytes, pos: 0 };
+                }
+                Poll::Ready(None) => return Poll::Ready(Ok(0)),
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+    }
+}
+
+impl AsyncWrite for Framed {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        _: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        if self.state.get() == State::Exited {
+            return Poll::Ready(Err(io::ErrorKind::BrokenPipe.into()));
+        }
+
+        self.write_buf.extend_from_slice(buf);
+
+        while let Some(body) = self.take_message()? {
+            let response: Response = serde_json::from_slice(&body).map_err(io::Error::other)?;
+            self.pending.complete(response);
+        }
+
+        Poll::Ready(Ok(buf.len()))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_close(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        match Sink::<Response>::poll_close(Pin::new(&mut self.rx), cx) {
+            Poll::Ready(Ok(())) => {
+                self.state.set(State::Exited);
+                Poll::Ready(Ok(()))
+            }
+            Poll::Ready(Err(err)) => Poll::Ready(Err(io::Error::other(err))),
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
+/// A [`tokio::io::AsyncRead`] + [`tokio::io::AsyncWrite`] variant of [`Framed`], for
+/// embedders already committed to the Tokio runtime.
+#[cfg(feature = "runtime-tokio")]
+mod tokio_compat {
+    use std::pin::Pin;
+    use std::task::{Context, Poll};
+
+    use futures::io::AsyncRead as _;
+    use tokio::io::ReadBuf;
+
+    use super::Framed;
+
+    impl tokio::io::AsyncRead for Framed {
+        fn poll_read(
+            mut self: Pin<&mut Self>,
+            cx: &mut Context<'_>,
+            buf: &mut ReadBuf<'_>,
+        ) -> Poll<std::io::Result<()>> {
+            let dst = buf.initialize_unfilled();
+            match Pin::new(&mut *self).poll_read(cx, dst) {
+                Poll::Ready(Ok(n)) => {
+                    buf.advance(n);
+                    Poll::Ready(Ok(()))
+                }
+                Poll::Ready(Err(err)) => Poll::Ready(Err(err)),
+                Poll::Pending => Poll::Pending,
+            }
+        }
+    }
+
+    impl tokio::io::AsyncWrite for Framed {
+        fn poll_write(
+            self: Pin<&mut Self>,
+            cx: &mut Context<'_>,
+            buf: &[u8],
+        ) -> Poll<std::io::Result<usize>> {
+            futures::io::AsyncWrite::poll_write(self, cx, buf)
+        }
+
+        fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+            futures::io::AsyncWrite::poll_flush(self, cx)
+        }
+
+        fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+            futures::io::AsyncWrite::poll_close(self, cx)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn framed_with(bytes: &[u8]) -> Framed {
+        let (tx, rx) = futures::channel::mpsc::channel(8);
+        drop(tx);
+        Framed::new(ClientSocket {
+            rx,
+            pending: std::sync::Arc::new(Pending::default()),
+            state: std::sync::Arc::new(ServerState::default()),
+        })
+        .with_write_buf(bytes)
+    }
+
+    impl Framed {
+        /// Test-only helper to seed `write_buf` directly, bypassing `poll_write`.
+        fn with_write_buf(mut self, bytes: &[u8]) -> Self {
+            self.write_buf.extend_from_slice(bytes);
+            self
+        }
+    }
+
+    #[test]
+    fn take_message_returns_none_until_the_body_is_complete() {
+        let mut framed = framed_with(b"Content-Length: 5\r\n\r\nhel");
+        assert!(
+            framed.take_message().unwrap().is_none(),
+            "body is still partial"
+        );
+
+        framed.write_buf.extend_from_slice(b"lo");
+        assert_eq!(
+            framed.take_message().unwrap().as_deref(),
+            Some(&b"hello"[..])
+        );
+        assert!(framed.write_buf.is_empty());
+    }
+
+    #[test]
+    fn take_message_returns_none_until_the_header_is_complete() {
+        let mut framed = framed_with(b"Content-Length: 5\r\n\r");
+        assert!(
+            framed.take_message().unwrap().is_none(),
+            "header terminator is split across writes"
+        );
+
+        framed.write_buf.extend_from_slice(b"\nhello");
+        assert_eq!(
+            framed.take_message().unwrap().as_deref(),
+            Some(&b"hello"[..])
+        );
+    }
+
+    #[test]
+    fn take_message_leaves_a_trailing_partial_message_buffered() {
+        let mut framed = framed_with(b"Content-Length: 2\r\n\r\nhiContent-Length: 3\r\n\r\nbo");
+
+        assert_eq!(framed.take_message().unwrap().as_deref(), Some(&b"hi"[..]));
+        assert!(
+            framed.take_message().unwrap().is_none(),
+            "second message's body is still partial"
+        );
+        assert_eq!(framed.write_buf, b"Content-Length: 3\r\n\r\nbo");
+
+        framed.write_buf.extend_from_slice(b"o");
+        assert_eq!(framed.take_message().unwrap().as_deref(), Some(&b"boo"[..]));
+        assert!(framed.write_buf.is_empty());
+    }
+
+    #[test]
+    fn take_message_extracts_back_to_back_messages_in_one_buffer() {
+        let mut framed = framed_with(b"Content-Length: 2\r\n\r\nhiContent-Length: 2\r\n\r\nyo");
+
+        assert_eq!(framed.take_message().unwrap().as_deref(), Some(&b"hi"[..]));
+        assert_eq!(framed.take_message().unwrap().as_deref(), Some(&b"yo"[..]));
+        assert!(framed.take_message().unwrap().is_none());
+    }
+
+    #[test]
+    fn take_message_errors_on_non_utf8_header() {
+        let mut framed = framed_with(b"Content-Length: 5\r\xff\r\n\r\nhello");
+        let err = framed
+            .take_message()
+            .expect_err("header bytes are not valid UTF-8");
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn take_message_errors_on_missing_content_length() {
+        let mut framed = framed_with(b"X-Something: else\r\n\r\nhello");
+        let err = framed
+            .take_message()
+            .expect_err("no Content-Length header present");
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn take_message_errors_on_a_header_that_never_terminates() {
+        let mut framed = framed_with(&vec![b'a'; Framed::MAX_HEADER_LEN + 1]);
+        let err = framed
+            .take_message()
+            .expect_err("header exceeds the sanity cap without ever finding \\r\\n\\r\\n");
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn take_message_errors_on_a_content_length_past_the_cap() {
+        let header = format!("Content-Length: {}\r\n\r\n", Framed::MAX_BODY_LEN + 1);
+        let mut framed = framed_with(header.as_bytes());
+        let err = framed
+            .take_message()
+            .expect_err("declared Content-Length exceeds the sanity cap");
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+}
@@ -0,0 +1,575 @@
+//! State shared between the server and its loopback connection to the client.
+
+mod framed;
+mod socket;
+
+use std::cmp::Ordering as CmpOrdering;
+use std::collections::BinaryHeap;
+use std::collections::HashMap;
+use std::fmt;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU8, Ordering};
+use std::sync::{Arc, Condvar, Mutex, Once, Weak};
+use std::task::{Context, Poll, Waker};
+use std::time::{Duration, Instant};
+
+use futures::channel::mpsc;
+use futures::task::AtomicWaker;
+
+pub use framed::Framed;
+pub use socket::{ClientSocket, RequestStream, ResponseSink};
+
+use crate::jsonrpc::{Error, ErrorCode, Id, Request, Response};
+
+/// Error returned when attempting to use a [`ClientSocket`] after the server has exited.
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub struct ExitedError(pub(crate) ());
+
+impl fmt::Display for ExitedError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "language client has already exited")
+    }
+}
+
+impl std::error::Error for ExitedError {}
+
+/// Lifecycle of the loopback channel, shared between the server and its two halves.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+#[repr(u8)]
+pub(crate) enum State {
+    Initialized = 0,
+    Exited = 1,
+}
+
+/// Atomic storage for the current [`State`], plus the waker of whichever task is currently
+/// parked waiting for the transition into [`State::Exited`] (namely a [`RequestStream`]
+/// blocked in `poll_next`).
+#[derive(Debug, Default)]
+pub(crate) struct ServerState {
+    bits: AtomicU8,
+    /// Single-slot: a re-poll while still pending overwrites the stale waker rather than
+    /// accumulating one entry per poll for the life of the server.
+    exit: AtomicWaker,
+}
+
+impl ServerState {
+    pub fn get(&self) -> State {
+        match self.bits.load(Ordering::Acquire) {
+            1 => State::Exited,
+            _ => State::Initialized,
+        }
+    }
+
+    pub fn set(&self, state: State) {
+        self.bits.store(state as u8, Ordering::Release);
+        if state == State::Exited {
+            self.exit.wake();
+        }
+    }
+
+    /// Registers `waker` to be woken once this state transitions to [`State::Exited`],
+    /// replacing whatever waker was previously registered.
+    pub fn register_exit_waker(&self, waker: &Waker) {
+        self.exit.register(waker);
+    }
+}
+
+/// How an outstanding request was resolved, absent an actual client `Response`.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+enum Disposition {
+    #[default]
+    Outstanding,
+    Cancelled,
+    TimedOut,
+}
+
+/// A single outstanding server-to-client request, awaiting either a response from the
+/// client, cancellation, or its deadline to elapse.
+#[derive(Debug, Default)]
+struct Slot {
+    response: Option<Response>,
+    waker: Option<Waker>,
+    disposition: Disposition,
+}
+
+/// An `(Instant, Id)` pair ordered by deadline only, so [`Reaper`]'s heap doesn't need
+/// `Id: Ord` — nothing about request identity should affect firing order.
+#[derive(Debug)]
+struct Timer {
+    deadline: Instant,
+    id: Id,
+}
+
+impl PartialEq for Timer {
+    fn eq(&self, other: &Self) -> bool {
+        self.deadline == other.deadline
+    }
+}
+
+impl Eq for Timer {}
+
+impl PartialOrd for Timer {
+    fn partial_cmp(&self, other: &Self) -> Option<CmpOrdering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Timer {
+    fn cmp(&self, other: &Self) -> CmpOrdering {
+        self.deadline.cmp(&other.deadline)
+    }
+}
+
+/// The single background timer thread shared by every timed request in a `Pending`
+/// registry, so arming a timeout no longer costs a dedicated OS thread per request.
+///
+/// The thread (started lazily on first use, see `Pending::arm`) sleeps until the earliest
+/// queued deadline and then calls `Pending::expire` for it, which is a no-op if that
+/// request already resolved some other way — so entries for requests that finish early
+/// are simply left in the heap to be popped and discarded once their deadline arrives,
+/// rather than needing to be cancelled individually.
+#[derive(Debug, Default)]
+struct Reaper {
+    heap: Mutex<BinaryHeap<std::cmp::Reverse<Timer>>>,
+    wake: Condvar,
+    started: Once,
+}
+
+/// Tracks server-to-client requests that are awaiting a response from the client.
+#[derive(Debug)]
+pub(crate) struct Pending {
+    slots: Mutex<HashMap<Id, Slot>>,
+    /// Cap on the number of simultaneously outstanding requests; enforced by `poll_ready`.
+    capacity: usize,
+    /// Wakers of tasks blocked in `poll_ready`, woken once a slot frees up.
+    ready: Mutex<Vec<Waker>>,
+    reaper: Reaper,
+}
+
+impl Pending {
+    /// The default cap on the number of simultaneously outstanding client-bound requests.
+    const DEFAULT_CAPACITY: usize = 128;
+
+    pub fn new(capacity: usize) -> Self {
+        Pending {
+            slots: Mutex::default(),
+            capacity,
+            ready: Mutex::default(),
+            reaper: Reaper::default(),
+        }
+    }
+
+    /// Arms `id` to be expired at `deadline`, lazily starting this registry's single reaper
+    /// thread on first use rather than spawning a dedicated thread per request.
+    fn arm(self: &Arc<Self>, id: Id, deadline: Instant) {
+        self.reaper
+            .heap
+            .lock()
+            .unwrap()
+            .push(std::cmp::Reverse(Timer { deadline, id }));
+        self.reaper.wake.notify_one();
+
+        self.reaper.started.call_once(|| {
+            let pending = Arc::downgrade(self);
+            std::thread::spawn(move || Self::reap(pending));
+        });
+    }
+
+    /// Body of the reaper thread: repeatedly sleeps until the earliest queued deadline and
+    /// expires it, waking early whenever a new (possibly earlier) deadline is armed. Exits
+    /// once the owning `Pending` is dropped.
+    fn reap(pending: Weak<Pending>) {
+        // Bounds how long an idle reaper holds its strong reference, so that it can notice
+        // the owning `Pending` being dropped.
+        const IDLE_RECHECK: Duration = Duration::from_millis(100);
+
+        loop {
+            let Some(pending) = pending.upgrade() else {
+                return;
+            };
+
+            let mut heap = pending.reaper.heap.lock().unwrap();
+            let id = loop {
+                match heap.peek() {
+                    None => {
+                        let (guard, idle) =
+                            pending.reaper.wake.wait_timeout(heap, IDLE_RECHECK).unwrap();
+                        heap = guard;
+                        if idle.timed_out() && heap.is_empty() {
+                            break None;
+                        }
+                    }
+                    Some(std::cmp::Reverse(timer)) => {
+                        let now = Instant::now();
+                        if timer.deadline <= now {
+                            break Some(heap.pop().unwrap().0.id);
+                        }
+                        heap = pending
+                            .reaper
+                            .wake
+                            .wait_timeout(heap, timer.deadline - now)
+                            .unwrap()
+                            .0;
+                    }
+                }
+            };
+            drop(heap);
+
+            if let Some(id) = id {
+                pending.expire(&id);
+            }
+        }
+    }
+
+    /// Registers a new outstanding request.
+    ///
+    /// Callers must have first observed `poll_ready` return `Poll::Ready(())`.
+    fn insert(&self, id: Id) {
+        self.slots.lock().unwrap().insert(id, Slot::default());
+    }
+
+    /// Polls for capacity to register one more outstanding request, registering `cx`'s
+    /// waker and returning `Poll::Pending` once `capacity` outstanding requests are live.
+    fn poll_ready(&self, cx: &mut Context<'_>) -> Poll<()> {
+        if self.slots.lock().unwrap().len() < self.capacity {
+            Poll::Ready(())
+        } else {
+            self.ready.lock().unwrap().push(cx.waker().clone());
+            Poll::Pending
+        }
+    }
+
+    /// Wakes every task waiting in `poll_ready` for a slot to free up.
+    fn notify_ready(&self) {
+        for waker in self.ready.lock().unwrap().drain(..) {
+            waker.wake();
+        }
+    }
+
+    /// Routes a `Response` that just arrived from the client back to its waiter.
+    ///
+    /// Responses for ids that are no longer tracked (already timed out, cancelled, or
+    /// simply unrecognized) are dropped silently.
+    pub fn complete(&self, response: Response) {
+        let mut slots = self.slots.lock().unwrap();
+        let slot = slots.get_mut(response.id());
+        if let Some(slot) = slot.filter(|slot| slot.disposition == Disposition::Outstanding) {
+            slot.response = Some(response);
+            if let Some(waker) = slot.waker.take() {
+                waker.wake();
+            }
+        }
+    }
+
+    /// Cancels an outstanding request, waking its waiter with a "request cancelled" error.
+    ///
+    /// Returns `false` if `id` was not (or is no longer) outstanding.
+    fn cancel(&self, id: &Id) -> bool {
+        let mut slots = self.slots.lock().unwrap();
+        match slots.get_mut(id) {
+            Some(slot) if slot.disposition == Disposition::Outstanding && slot.response.is_none() => {
+                slot.disposition = Disposition::Cancelled;
+                if let Some(waker) = slot.waker.take() {
+                    waker.wake();
+                }
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Cancels every still-outstanding request, waking their waiters with a "request
+    /// cancelled" error. Used when gracefully closing the loopback.
+    fn cancel_all(&self) {
+        for slot in self.slots.lock().unwrap().values_mut() {
+            if slot.disposition == Disposition::Outstanding {
+                slot.disposition = Disposition::Cancelled;
+                if let Some(waker) = slot.waker.take() {
+                    waker.wake();
+                }
+            }
+        }
+    }
+
+    /// Expires `id` if it is still outstanding, waking its waiter with a "request timed
+    /// out" error. Called by this registry's [`Reaper`] thread, armed via [`Pending::arm`],
+    /// which is what actually guarantees a timeout fires on its own rather than only when
+    /// `poll` happens to be re-driven by some unrelated event. A no-op if `id` already
+    /// resolved some other way, which is what lets stale reaper entries be discarded
+    /// instead of needing to be cancelled individually.
+    fn expire(&self, id: &Id) {
+        let mut slots = self.slots.lock().unwrap();
+        if let Some(slot) = slots.get_mut(id) {
+            if slot.disposition == Disposition::Outstanding {
+                slot.disposition = Disposition::TimedOut;
+                if let Some(waker) = slot.waker.take() {
+                    waker.wake();
+                }
+            }
+        }
+    }
+
+    /// Unconditionally removes `id`'s slot, if still present, freeing its place under the
+    /// capacity cap and waking anyone blocked in `poll_ready`/`poll_drained`.
+    ///
+    /// Normal resolution already removes the slot from inside `poll` — that only happens
+    /// because the `PendingRequest` future is polled again, so it can't be relied on for a
+    /// request whose future is dropped before resolving (`poll` will simply never run
+    /// again). `PendingRequest`'s `Drop` impl calls this to guarantee the slot is evicted
+    /// either way, so a caller abandoning a request can't wedge a concurrent graceful close
+    /// waiting in `poll_drained` forever.
+    fn evict(&self, id: &Id) {
+        if self.slots.lock().unwrap().remove(id).is_some() {
+            self.notify_ready();
+        }
+    }
+
+    /// Polls whether every outstanding request has been evicted (answered, cancelled, or
+    /// timed out), registering `cx`'s waker to be woken as slots are removed otherwise.
+    fn poll_drained(&self, cx: &mut Context<'_>) -> Poll<()> {
+        if self.slots.lock().unwrap().is_empty() {
+            Poll::Ready(())
+        } else {
+            self.ready.lock().unwrap().push(cx.waker().clone());
+            Poll::Pending
+        }
+    }
+
+    /// Polls the outcome of the request `id`, evicting its slot once resolved.
+    fn poll(&self, id: &Id, cx: &mut Context<'_>) -> Poll<Result<Response, Error>> {
+        let mut slots = self.slots.lock().unwrap();
+        let slot = match slots.get_mut(id) {
+            Some(slot) => slot,
+            None => return Poll::Ready(Err(Error::invalid_request())),
+        };
+
+        if let Some(response) = slot.response.take() {
+            slots.remove(id);
+            drop(slots);
+            self.notify_ready();
+            return Poll::Ready(Ok(response));
+        }
+
+        match slot.disposition {
+            Disposition::Cancelled => {
+                slots.remove(id);
+                drop(slots);
+                self.notify_ready();
+                return Poll::Ready(Err(request_cancelled()));
+            }
+            Disposition::TimedOut => {
+                slots.remove(id);
+                drop(slots);
+                self.notify_ready();
+                return Poll::Ready(Err(request_timed_out()));
+            }
+            Disposition::Outstanding => {}
+        }
+
+        slot.waker = Some(cx.waker().clone());
+        Poll::Pending
+    }
+}
+
+impl Default for Pending {
+    fn default() -> Self {
+        Pending::new(Self::DEFAULT_CAPACITY)
+    }
+}
+
+fn request_cancelled() -> Error {
+    Error {
+        code: ErrorCode::ServerError(-32800),
+        message: "request cancelled".into(),
+        data: None,
+    }
+}
+
+fn request_timed_out() -> Error {
+    Error {
+        code: ErrorCode::ServerError(-32000),
+        message: "request timed out waiting for a client response".into(),
+        data: None,
+    }
+}
+
+/// Awaits the response to a single outstanding server-to-client request.
+///
+/// Dropping a `PendingRequest` before it resolves evicts its slot — freeing its place under
+/// `Pending`'s capacity cap and letting a concurrent graceful close make progress — but does
+/// not by itself notify the client; get a [`CancellationHandle`] via
+/// [`cancellation_handle`](PendingRequest::cancellation_handle) and call
+/// [`cancel`](CancellationHandle::cancel) first if the client should stop any in-progress work.
+#[derive(Debug)]
+#[must_use = "futures do nothing unless awaited"]
+pub(crate) struct PendingRequest {
+    id: Id,
+    pending: Arc<Pending>,
+    notify: mpsc::Sender<Request>,
+}
+
+impl PendingRequest {
+    /// Awaits capacity under `Pending`'s outstanding-request cap, then registers `id` as
+    /// outstanding and returns a future that resolves once the client answers, the request
+    /// is cancelled, or `timeout` elapses (if given).
+    ///
+    /// `notify` is the same sender the request itself was issued through; cancelling this
+    /// request pushes a `$/cancelRequest` notification back through it.
+    pub async fn new(
+        id: Id,
+        pending: Arc<Pending>,
+        timeout: Option<Duration>,
+        notify: mpsc::Sender<Request>,
+    ) -> Self {
+        std::future::poll_fn(|cx| pending.poll_ready(cx)).await;
+        pending.insert(id.clone());
+
+        // This crate stays executor-agnostic, so there is no shared reactor to hand a
+        // sleep future to; `Pending::arm` guarantees `expire` fires at `timeout` on its
+        // own, rather than only when `Pending::poll` happens to be re-driven by some
+        // unrelated wakeup, without needing a dedicated OS thread per timed request.
+        if let Some(timeout) = timeout {
+            pending.arm(id.clone(), Instant::now() + timeout);
+        }
+
+        PendingRequest {
+            id,
+            pending,
+            notify,
+        }
+    }
+
+    /// A handle that can be used to cancel this request independently of awaiting it.
+    pub fn cancellation_handle(&self) -> CancellationHandle {
+        CancellationHandle {
+            id: self.id.clone(),
+            pending: self.pending.clone(),
+            notify: self.notify.clone(),
+        }
+    }
+}
+
+impl Future for PendingRequest {
+    type Output = Result<Response, Error>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        self.pending.poll(&self.id, cx)
+    }
+}
+
+impl Drop for PendingRequest {
+    fn drop(&mut self) {
+        // `poll` already removes the slot once this future resolves, so this is a no-op in
+        // the normal await-to-completion case; it only does real work for a request that's
+        // abandoned (dropped) before that, which would otherwise leave its slot, and the
+        // capacity it holds, stuck forever.
+        self.pending.evict(&self.id);
+    }
+}
+
+/// A handle that can cancel a single in-flight server-to-client request, keyed by its id.
+#[derive(Debug, Clone)]
+pub struct CancellationHandle {
+    id: Id,
+    pending: Arc<Pending>,
+    notify: mpsc::Sender<Request>,
+}
+
+impl CancellationHandle {
+    /// Cancels the request, waking its waiter with a "request cancelled" error and pushing
+    /// a `$/cancelRequest` notification through the `RequestStream` so the client can stop
+    /// any in-progress work. A no-op if the request already completed, timed out, or was
+    /// already cancelled.
+    ///
+    /// Returns `false` if the request was not (or is no longer) outstanding.
+    pub fn cancel(&self) -> bool {
+        if !self.pending.cancel(&self.id) {
+            return false;
+        }
+
+        // Best-effort: if the channel is full or the loopback has already exited, the
+        // client was either never going to see this notification in time or has nothing
+        // left to cancel, so a dropped notification here is not itself an error.
+        let _ = self.notify.clone().try_send(Request::from_notification(
+            "$/cancelRequest",
+            Some(serde_json::json!({ "id": self.id })),
+        ));
+
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::executor::block_on;
+    use futures::StreamExt;
+
+    use super::*;
+
+    fn channel() -> (mpsc::Sender<Request>, mpsc::Receiver<Request>) {
+        mpsc::channel(8)
+    }
+
+    #[test]
+    fn timeout_resolves_without_being_repolled() {
+        // `block_on` only repolls the future when its waker fires, so this only passes if
+        // the background timer armed in `PendingRequest::new` wakes it on its own, rather
+        // than relying on some unrelated event to re-drive `Pending::poll` reactively.
+        let pending = Arc::new(Pending::default());
+        let (tx, _rx) = channel();
+
+        let request = block_on(PendingRequest::new(
+            Id::Number(1),
+            pending,
+            Some(Duration::from_millis(20)),
+            tx,
+        ));
+
+        match block_on(request) {
+            Err(err) => assert_eq!(err.code, ErrorCode::ServerError(-32000)),
+            Ok(_) => panic!("expected the request to time out"),
+        }
+    }
+
+    #[test]
+    fn cancel_resolves_the_request_and_notifies_the_client() {
+        let pending = Arc::new(Pending::default());
+        let (tx, mut rx) = channel();
+
+        let request = block_on(PendingRequest::new(Id::Number(1), pending, None, tx));
+        let handle = request.cancellation_handle();
+
+        std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(20));
+            assert!(handle.cancel());
+        });
+
+        match block_on(request) {
+            Err(err) => assert_eq!(err.code, ErrorCode::ServerError(-32800)),
+            Ok(_) => panic!("expected the request to be cancelled"),
+        }
+
+        let notification = block_on(rx.next()).expect("cancel() should notify the client");
+        assert_eq!(notification.method(), "$/cancelRequest");
+    }
+
+    #[test]
+    fn cancelling_a_finished_request_is_a_no_op() {
+        let pending = Arc::new(Pending::default());
+        let (tx, mut rx) = channel();
+        let id = Id::Number(1);
+
+        let request = block_on(PendingRequest::new(id.clone(), pending.clone(), None, tx));
+        let handle = request.cancellation_handle();
+
+        pending.complete(Response::from_ok(id, serde_json::json!(null)));
+        assert_eq!(block_on(request).unwrap().id(), &Id::Number(1));
+
+        assert!(!handle.cancel());
+        assert!(
+            rx.try_next().is_err(),
+            "no notification should be sent for a request that already resolved"
+        );
+    }
+}
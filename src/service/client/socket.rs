@@ -35,6 +35,54 @@ impl ClientSocket {
             ResponseSink { pending, state },
         )
     }
+
+    /// Bridges this `ClientSocket` to an arbitrary byte transport (a named pipe, TCP
+    /// socket, WebSocket, ...) by framing requests and responses as
+    /// `Content-Length: N\r\n\r\n{json}`, the wire format LSP uses over stdio.
+    ///
+    /// The returned [`Framed`] implements [`futures::AsyncRead`] + [`futures::AsyncWrite`];
+    /// enable the `runtime-tokio` feature for a [`tokio::io::AsyncRead`] +
+    /// [`tokio::io::AsyncWrite`] impl as well.
+    pub fn into_framed(self) -> super::Framed {
+        super::Framed::new(self)
+    }
+
+    /// Polls for the next client-bound request on this handle's [`Stream`] half, so callers
+    /// that only need to pull requests don't have to import [`StreamExt`](futures::StreamExt)
+    /// themselves.
+    pub fn poll_next_request(&mut self, cx: &mut Context<'_>) -> Poll<Option<Request>> {
+        Pin::new(self).poll_next(cx)
+    }
+
+    /// Awaits the next client-bound request, resolving to `None` once the loopback closes.
+    pub async fn next_request(&mut self) -> Option<Request> {
+        std::future::poll_fn(|cx| self.poll_next_request(cx)).await
+    }
+
+    /// Polls this handle's [`Sink`] half for readiness, so callers that only need to route
+    /// responses back don't have to import [`SinkExt`](futures::SinkExt) themselves.
+    pub fn poll_send_response(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), ExitedError>> {
+        Pin::new(self).poll_ready(cx)
+    }
+
+    /// Routes `response` back to the server, failing if the loopback has already exited.
+    ///
+    /// This never blocks on the outstanding-request cap — that's enforced where requests
+    /// are issued, not here, since gating completion on it would deadlock (the only way a
+    /// slot frees up is by routing its response through this very method).
+    pub async fn send_response(&mut self, response: Response) -> Result<(), ExitedError> {
+        std::future::poll_fn(|cx| self.poll_send_response(cx)).await?;
+        Pin::new(self).start_send(response)
+    }
+
+    /// Gracefully tears down the loopback: every outstanding client-bound request is
+    /// resolved (answered or cancelled) before the channel is marked closed, rather than
+    /// dropping them mid-flight.
+    pub async fn close_gracefully(&mut self) {
+        std::future::poll_fn(|cx| Sink::<Response>::poll_close(Pin::new(self), cx))
+            .await
+            .ok();
+    }
 }
 
 /// Yields a stream of pending server-to-client requests.
@@ -43,9 +91,17 @@ impl Stream for ClientSocket {
 
     fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
         if self.state.get() == State::Exited || self.rx.is_terminated() {
-            Poll::Ready(None)
-        } else {
-            self.rx.poll_next_unpin(cx)
+            return Poll::Ready(None);
+        }
+
+        match self.rx.poll_next_unpin(cx) {
+            Poll::Pending => {
+                // Registered so a concurrent graceful close can wake us up to yield `None`
+                // as soon as the loopback finishes draining, rather than only on new items.
+                self.state.register_exit_waker(cx.waker());
+                Poll::Pending
+            }
+            ready => ready,
         }
     }
 
@@ -67,6 +123,10 @@ impl Sink<Response> for ClientSocket {
     type Error = ExitedError;
 
     fn poll_ready(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        // The outstanding-request cap lives on the issuing side (`PendingRequest::new`);
+        // this sink only ever *completes* existing slots, so it must stay unconditionally
+        // ready — gating it on `Pending`'s capacity would deadlock once full, since the
+        // only way a slot frees up is by routing its response through this very sink.
         if self.state.get() == State::Exited || self.rx.is_terminated() {
             Poll::Ready(Err(ExitedError(())))
         } else {
@@ -75,7 +135,9 @@ impl Sink<Response> for ClientSocket {
     }
 
     fn start_send(self: Pin<&mut Self>, item: Response) -> Result<(), Self::Error> {
-        self.pending.insert(item);
+        // A response may arrive for an id whose `PendingRequest` already timed out or was
+        // cancelled; `Pending::complete` drops those silently instead of erroring here.
+        self.pending.complete(item);
         Ok(())
     }
 
@@ -83,8 +145,30 @@ impl Sink<Response> for ClientSocket {
         Poll::Ready(Ok(()))
     }
 
-    fn poll_close(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
-        Poll::Ready(Ok(()))
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        poll_close_gracefully(&self.pending, &self.state, cx)
+    }
+}
+
+/// Drains `pending` gracefully: every buffered response has already been inserted
+/// synchronously by `start_send`, so this only needs to wait for every outstanding
+/// client-bound request to resolve (cancelling the stragglers) before marking `state`
+/// exited, which in turn wakes the paired [`RequestStream`]/[`ClientSocket`] to yield `None`.
+fn poll_close_gracefully(
+    pending: &Pending,
+    state: &ServerState,
+    cx: &mut Context<'_>,
+) -> Poll<Result<(), ExitedError>> {
+    if state.get() != State::Exited {
+        pending.cancel_all();
+    }
+
+    match pending.poll_drained(cx) {
+        Poll::Ready(()) => {
+            state.set(State::Exited);
+            Poll::Ready(Ok(()))
+        }
+        Poll::Pending => Poll::Pending,
     }
 }
 
@@ -101,9 +185,15 @@ impl Stream for RequestStream {
 
     fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
         if self.state.get() == State::Exited || self.rx.is_terminated() {
-            Poll::Ready(None)
-        } else {
-            self.rx.poll_next_unpin(cx)
+            return Poll::Ready(None);
+        }
+
+        match self.rx.poll_next_unpin(cx) {
+            Poll::Pending => {
+                self.state.register_exit_waker(cx.waker());
+                Poll::Pending
+            }
+            ready => ready,
         }
     }
 
@@ -120,8 +210,27 @@ impl FusedStream for RequestStream {
     }
 }
 
+impl RequestStream {
+    /// Polls this stream directly, as an inherent method so callers don't need to import
+    /// [`StreamExt`](futures::StreamExt) just to call `poll_next`.
+    pub fn poll_next_request(&mut self, cx: &mut Context<'_>) -> Poll<Option<Request>> {
+        Pin::new(self).poll_next(cx)
+    }
+
+    /// Awaits the next client-bound request, resolving to `None` once the paired
+    /// [`ResponseSink`] (or the original unsplit [`ClientSocket`]) has been closed.
+    pub async fn next_request(&mut self) -> Option<Request> {
+        std::future::poll_fn(|cx| self.poll_next_request(cx)).await
+    }
+}
+
 /// Routes client-to-server responses back to the server.
-#[derive(Debug)]
+///
+/// `Clone`d handles share the same underlying [`Pending`] registry, so multiple tasks
+/// (e.g. a primary stdio transport plus an auxiliary control channel) can each hold a
+/// `ResponseSink` and route responses back concurrently without wrapping it in a mutex:
+/// `Pending::complete` takes the registry's lock, so concurrent completions are safe.
+#[derive(Clone, Debug)]
 pub struct ResponseSink {
     pending: Arc<Pending>,
     state: Arc<ServerState>,
@@ -139,15 +248,35 @@ impl Sink<Response> for ResponseSink {
     }
 
     fn start_send(self: Pin<&mut Self>, item: Response) -> Result<(), Self::Error> {
-        self.pending.insert(item);
+        self.pending.complete(item);
         Ok(())
     }
 
     fn poll_flush(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        // `start_send` completes synchronously into `Pending`, so there is nothing left
+        // buffered to drain once it returns: honor the `Sink` contract without a queue.
         Poll::Ready(Ok(()))
     }
 
-    fn poll_close(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
-        Poll::Ready(Ok(()))
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        poll_close_gracefully(&self.pending, &self.state, cx)
+    }
+}
+
+impl ResponseSink {
+    /// Polls this sink's readiness directly, as an inherent method so callers don't need to
+    /// import [`SinkExt`](futures::SinkExt) just to call `poll_ready`.
+    pub fn poll_send_response(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), ExitedError>> {
+        Pin::new(self).poll_ready(cx)
+    }
+
+    /// Routes `response` back to the server, failing if the loopback has already exited.
+    ///
+    /// This never blocks on the outstanding-request cap — that's enforced where requests
+    /// are issued, not here, since gating completion on it would deadlock (the only way a
+    /// slot frees up is by routing its response through this very method).
+    pub async fn send_response(&mut self, response: Response) -> Result<(), ExitedError> {
+        std::future::poll_fn(|cx| self.poll_send_response(cx)).await?;
+        Pin::new(self).start_send(response)
     }
 }